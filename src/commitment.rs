@@ -0,0 +1,53 @@
+//! The original ask behind this module was protection against a malicious
+//! or buggy server that returns arbitrary ciphertexts: a succinct proof that
+//! the output ciphertexts equal the homomorphic evaluation of the committed
+//! circuit on the committed inputs. That is out of scope here — verifying a
+//! BFV circuit's evaluation needs a general-purpose succinct proof system
+//! (e.g. a SNARK over the circuit), which neither this crate nor `sunscreen`
+//! provides, and building one is a research-grade undertaking of its own.
+//!
+//! [`Commitment`] is a narrower primitive: it lets a client detect a result
+//! that was tampered with, substituted, or replayed *after* the server
+//! committed to it. A malicious server can still commit to and return wrong
+//! outputs it computed (or simply made up) itself. Treat the original
+//! request as unresolved, not as satisfied by this.
+
+use blake2::{Blake2b512, Digest};
+use sunscreen::Ciphertext;
+
+/// Hashes every public value of one program execution — the program's
+/// identifier, its input ciphertexts, and its claimed output ciphertexts —
+/// into a single Blake2b-512 digest, so tampering with any of the three
+/// changes the digest.
+fn transcript(program_id: &str, inputs: &[Ciphertext], outputs: &[Ciphertext]) -> [u8; 64] {
+    let mut hasher = Blake2b512::new();
+    hasher.update(program_id.as_bytes());
+    for input in inputs {
+        hasher.update(bincode::serialize(input).unwrap());
+    }
+    for output in outputs {
+        hasher.update(bincode::serialize(output).unwrap());
+    }
+    hasher.finalize().into()
+}
+
+/// A commitment to one program execution's public values, produced by
+/// whoever ran `program_id` on `inputs` and got `outputs`. See the module
+/// docs for what this does and does not protect against.
+pub struct Commitment {
+    program_id: String,
+    challenge: [u8; 64],
+}
+
+impl Commitment {
+    pub fn new(program_id: &str, inputs: &[Ciphertext], outputs: &[Ciphertext]) -> Self {
+        Self {
+            program_id: program_id.to_string(),
+            challenge: transcript(program_id, inputs, outputs),
+        }
+    }
+
+    pub fn matches(&self, inputs: &[Ciphertext], outputs: &[Ciphertext]) -> bool {
+        transcript(&self.program_id, inputs, outputs) == self.challenge
+    }
+}
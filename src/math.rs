@@ -7,6 +7,20 @@ use sunscreen::{
 
 pub const VEC_SIZE: usize = 5;
 
+/// Number of sufficient statistics per client: `S_x`, `S_y`, `S_xy`, `S_xx`, `n`.
+pub const STATS_SIZE: usize = 5;
+
+/// Newton–Raphson updates `rmse` applies to approximate a square root
+/// in-circuit. Near `value = 0` the `value / g` correction term vanishes and
+/// the iteration degenerates to halving `g` each step instead of converging
+/// quadratically, so this is sized for that worst case: from
+/// `g_0 = RMSE_SQRT_SEED`, getting under `1e-5` takes
+/// `log2(g_0 / 1e-5) ≈ 20` halvings; 22 leaves headroom.
+const NR_ITERATIONS: usize = 22;
+
+/// Plaintext seed `g_0` the Newton–Raphson iteration in `rmse` starts from.
+const RMSE_SQRT_SEED: f64 = 10.0;
+
 fn mean_impl<T>(input: [T; VEC_SIZE]) -> T
 where
     T: Div<f64, Output = T> + Add<Output = T> + Copy,
@@ -60,15 +74,39 @@ where
     var / x.len() as f64
 }
 
-fn fit_impl<T>(x: [T; VEC_SIZE], y: [T; VEC_SIZE], var_x_inv: T) -> (T, T)
+fn fit_impl<T>(x: [T; VEC_SIZE], y: [T; VEC_SIZE], one: T) -> (T, T)
 where
-    T: Div<f64, Output = T> + Mul<Output = T> + Add<Output = T> + Sub<Output = T> + Copy,
+    T: Div<f64, Output = T> + Div<Output = T> + Mul<Output = T> + Add<Output = T> + Sub<Output = T> + Copy,
 {
+    let var_x_inv = one / variance_impl(x);
     let coefficient = covariance_impl(x, y) * var_x_inv;
     let intercept = mean_impl(y) - coefficient * mean_impl(x);
     (intercept, coefficient)
 }
 
+fn sum_stats_impl<T>(a: [T; STATS_SIZE], b: [T; STATS_SIZE]) -> [T; STATS_SIZE]
+where
+    T: Add<Output = T> + Copy,
+{
+    let mut sum = a;
+    for i in 0..STATS_SIZE {
+        sum[i] = a[i] + b[i];
+    }
+    sum
+}
+
+fn fit_stats_impl<T>(stats: [T; STATS_SIZE]) -> (T, T)
+where
+    T: Mul<Output = T> + Sub<Output = T> + Div<Output = T> + Copy,
+{
+    let [s_x, s_y, s_xy, s_xx, n] = stats;
+    let cov_num = n * s_xy - s_x * s_y;
+    let var_num = n * s_xx - s_x * s_x;
+    let coefficient = cov_num / var_num;
+    let intercept = (s_y - coefficient * s_x) / n;
+    (intercept, coefficient)
+}
+
 fn predict_impl<T>(intercept: T, coefficient: T, x: T) -> T
 where
     T: Mul<Output = T> + Add<Output = T> + Sub<Output = T> + Copy,
@@ -76,12 +114,55 @@ where
     intercept + coefficient * x
 }
 
-// fn predict_list_impl<T>(intercept: T, coefficient: T, x: [T; VEC_SIZE]) -> T
-// where
-//     T: Mul<Output = T> + Add<Output = T> + Sub<Output = T> + Copy,
-// {
+fn mse_impl<T>(y_pred: [T; VEC_SIZE], y_test: [T; VEC_SIZE]) -> T
+where
+    T: Sub<Output = T> + Mul<Output = T> + Add<Output = T> + Div<f64, Output = T> + Copy,
+{
+    let mut sum_squared_error = (y_pred[0] - y_test[0]) * (y_pred[0] - y_test[0]);
+    for i in 1..VEC_SIZE {
+        let error = y_pred[i] - y_test[i];
+        sum_squared_error = sum_squared_error + error * error;
+    }
+    sum_squared_error / VEC_SIZE as f64
+}
 
-// }
+/// Approximates `sqrt(value)` with a fixed number of Newton–Raphson updates
+/// `g_{k+1} = (g_k + value / g_k) / 2`, starting from the plaintext `seed`.
+fn sqrt_newton_raphson_impl<T>(value: T, seed: f64, iterations: usize) -> T
+where
+    T: Add<Output = T> + Add<f64, Output = T> + Div<Output = T> + Div<f64, Output = T> + Copy,
+{
+    let mut g = (value / seed + seed) / 2.0;
+    for _ in 1..iterations {
+        g = (g + value / g) / 2.0;
+    }
+    g
+}
+
+fn rmse_impl<T>(y_pred: [T; VEC_SIZE], y_test: [T; VEC_SIZE]) -> T
+where
+    T: Sub<Output = T>
+        + Mul<Output = T>
+        + Add<Output = T>
+        + Add<f64, Output = T>
+        + Div<Output = T>
+        + Div<f64, Output = T>
+        + Copy,
+{
+    let mse = mse_impl(y_pred, y_test);
+    sqrt_newton_raphson_impl(mse, RMSE_SQRT_SEED, NR_ITERATIONS)
+}
+
+fn predict_list_impl<T>(intercept: T, coefficient: T, x: [T; VEC_SIZE]) -> [T; VEC_SIZE]
+where
+    T: Mul<Output = T> + Add<Output = T> + Sub<Output = T> + Copy,
+{
+    let mut predictions = [predict_impl(intercept, coefficient, x[0]); VEC_SIZE];
+    for i in 1..VEC_SIZE {
+        predictions[i] = predict_impl(intercept, coefficient, x[i]);
+    }
+    predictions
+}
 
 type CFrac = Cipher<Fractional<64>>;
 type CRational = Cipher<Rational>;
@@ -107,15 +188,39 @@ fn mean_absolute_error(y_pred: [CFrac; VEC_SIZE], y_test: [CFrac; VEC_SIZE]) ->
 }
 
 #[fhe_program(scheme = "bfv")]
-pub fn fit(x: [CFrac; VEC_SIZE], y: [CFrac; VEC_SIZE], var_x_inv: CFrac) -> (CFrac, CFrac) {
-    fit_impl(x, y, var_x_inv)
+pub fn fit(x: [CRational; VEC_SIZE], y: [CRational; VEC_SIZE], one: CRational) -> (CRational, CRational) {
+    fit_impl(x, y, one)
 }
 
 #[fhe_program(scheme = "bfv")]
-pub fn predict(intercept: CFrac, coefficient: CFrac, x: CFrac) -> CFrac {
+pub fn predict(intercept: CRational, coefficient: CRational, x: CRational) -> CRational {
     predict_impl(intercept, coefficient, x)
 }
 
+#[fhe_program(scheme = "bfv")]
+pub fn sum_stats(a: [CRational; STATS_SIZE], b: [CRational; STATS_SIZE]) -> [CRational; STATS_SIZE] {
+    sum_stats_impl(a, b)
+}
+
+#[fhe_program(scheme = "bfv")]
+pub fn fit_stats(stats: [CRational; STATS_SIZE]) -> (CRational, CRational) {
+    fit_stats_impl(stats)
+}
+
+#[fhe_program(scheme = "bfv")]
+pub fn rmse(y_pred: [CRational; VEC_SIZE], y_test: [CRational; VEC_SIZE]) -> CRational {
+    rmse_impl(y_pred, y_test)
+}
+
+#[fhe_program(scheme = "bfv")]
+pub fn predict_batch(
+    intercept: CRational,
+    coefficient: CRational,
+    x: [CRational; VEC_SIZE],
+) -> [CRational; VEC_SIZE] {
+    predict_list_impl(intercept, coefficient, x)
+}
+
 #[cfg(test)]
 mod test {
     use sunscreen::{Application, Compiler, FheProgramInput, PrivateKey, PublicKey, Runtime, types::{TypeName, TryIntoPlaintext}, PlainModulusConstraint};
@@ -125,6 +230,15 @@ mod test {
     const INPUTS_X: [f64; VEC_SIZE] = [1f64, 2f64, 3f64, 4f64, 5f64];
     const INPUTS_Y: [f64; VEC_SIZE] = [0.5f64, 1f64, 2.5f64, 3f64, 3.25f64];
 
+    fn local_stats(x: [f64; VEC_SIZE], y: [f64; VEC_SIZE]) -> [f64; STATS_SIZE] {
+        let n = VEC_SIZE as f64;
+        let s_x: f64 = x.iter().sum();
+        let s_y: f64 = y.iter().sum();
+        let s_xy: f64 = x.iter().zip(y.iter()).map(|(xi, yi)| xi * yi).sum();
+        let s_xx: f64 = x.iter().map(|xi| xi * xi).sum();
+        [s_x, s_y, s_xy, s_xx, n]
+    }
+
     fn encrypt_vec<T>(
         inputs: [f64; VEC_SIZE],
         runtime: &Runtime,
@@ -150,6 +264,10 @@ mod test {
             .fhe_program(mean_absolute_error)
             .fhe_program(fit)
             .fhe_program(predict)
+            .fhe_program(predict_batch)
+            .fhe_program(sum_stats)
+            .fhe_program(fit_stats)
+            .fhe_program(rmse)
             .compile()
             .unwrap();
         let runtime = Runtime::new(app.params()).unwrap();
@@ -237,24 +355,23 @@ mod test {
     fn test_fhe_fit() {
         let (app, runtime, public_key, private_key) = make_app();
 
-        let enc_inputs_x = encrypt_vec::<Fractional<64>>(INPUTS_X, &runtime, &public_key);
-        let enc_inputs_y = encrypt_vec::<Fractional<64>>(INPUTS_Y, &runtime, &public_key);
-        let var_x = Fractional::<64>::from(variance_impl(INPUTS_X));
-        let enc_var_x_inv = runtime.encrypt(var_x, &public_key).unwrap();
+        let enc_inputs_x = encrypt_vec::<Rational>(INPUTS_X, &runtime, &public_key);
+        let enc_inputs_y = encrypt_vec::<Rational>(INPUTS_Y, &runtime, &public_key);
+        let enc_one = runtime.encrypt(Rational::try_from(1.0).unwrap(), &public_key).unwrap();
 
         let arguments: Vec<FheProgramInput> =
-            vec![enc_inputs_x.into(), enc_inputs_y.into(), enc_var_x_inv.into()];
+            vec![enc_inputs_x.into(), enc_inputs_y.into(), enc_one.into()];
 
         let results = runtime
             .run(app.get_program(fit).unwrap(), arguments, &public_key)
             .unwrap();
 
         let actual: (f64, f64) = (
-            runtime.decrypt::<Fractional<64>>(&results[0], &private_key).unwrap().into(),
-            runtime.decrypt::<Fractional<64>>(&results[1], &private_key).unwrap().into(),
+            runtime.decrypt::<Rational>(&results[0], &private_key).unwrap().into(),
+            runtime.decrypt::<Rational>(&results[1], &private_key).unwrap().into(),
         );
-        let expected = fit_impl(INPUTS_X, INPUTS_Y, *var_x);
-        
+        let expected = fit_impl(INPUTS_X, INPUTS_Y, 1.0);
+
         assert!(f64::abs(actual.0 - expected.0) < 1e-5);
         assert!(f64::abs(actual.1 - expected.1) < 1e-5)
     }
@@ -263,16 +380,14 @@ mod test {
     fn test_fhe_predict() {
         let (app, runtime, public_key, private_key) = make_app();
 
-        let var_x = variance_impl(INPUTS_X);
-
-        let (intercept, coefficient) = fit_impl(INPUTS_X, INPUTS_Y, var_x);
+        let (intercept, coefficient) = fit_impl(INPUTS_X, INPUTS_Y, 1.0);
         let enc_intercept = runtime
-            .encrypt(Fractional::<64>::try_from(intercept).unwrap(), &public_key)
+            .encrypt(Rational::try_from(intercept).unwrap(), &public_key)
             .unwrap();
-        let enc_coefficient = runtime.encrypt(Fractional::<64>::try_from(coefficient).unwrap(), &public_key).unwrap();
+        let enc_coefficient = runtime.encrypt(Rational::try_from(coefficient).unwrap(), &public_key).unwrap();
         let input = 2f64;
         let enc_input = runtime
-            .encrypt(Fractional::<64>::try_from(input).unwrap(), &public_key)
+            .encrypt(Rational::try_from(input).unwrap(), &public_key)
             .unwrap();
         let arguments: Vec<FheProgramInput> = vec![
             enc_intercept.into(),
@@ -284,9 +399,113 @@ mod test {
             .run(app.get_program(predict).unwrap(), arguments, &public_key)
             .unwrap();
 
-        let actual: Fractional<64> = runtime.decrypt(&results[0], &private_key).unwrap();
+        let actual: Rational = runtime.decrypt(&results[0], &private_key).unwrap();
         let actual: f64 = actual.into();
         let expected = predict_impl(intercept, coefficient, input);
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_fhe_predict_batch() {
+        let (app, runtime, public_key, private_key) = make_app();
+
+        let (intercept, coefficient) = fit_impl(INPUTS_X, INPUTS_Y, 1.0);
+        let enc_intercept = runtime
+            .encrypt(Rational::try_from(intercept).unwrap(), &public_key)
+            .unwrap();
+        let enc_coefficient = runtime
+            .encrypt(Rational::try_from(coefficient).unwrap(), &public_key)
+            .unwrap();
+        let enc_inputs = encrypt_vec::<Rational>(INPUTS_X, &runtime, &public_key);
+
+        let arguments: Vec<FheProgramInput> = vec![
+            enc_intercept.into(),
+            enc_coefficient.into(),
+            enc_inputs.into(),
+        ];
+
+        let results = runtime
+            .run(app.get_program(predict_batch).unwrap(), arguments, &public_key)
+            .unwrap();
+
+        let actual: [Rational; VEC_SIZE] = runtime.decrypt(&results[0], &private_key).unwrap();
+        let expected = predict_list_impl(intercept, coefficient, INPUTS_X);
+        for i in 0..VEC_SIZE {
+            assert_eq!(f64::from(actual[i]), expected[i]);
+        }
+    }
+
+    #[test]
+    fn test_fhe_sum_stats() {
+        let (app, runtime, public_key, private_key) = make_app();
+
+        let stats_a = local_stats(INPUTS_X, INPUTS_Y);
+        let stats_b = local_stats(INPUTS_Y, INPUTS_X);
+        let enc_stats_a = encrypt_vec::<Rational>(stats_a, &runtime, &public_key);
+        let enc_stats_b = encrypt_vec::<Rational>(stats_b, &runtime, &public_key);
+
+        let arguments: Vec<FheProgramInput> = vec![enc_stats_a.into(), enc_stats_b.into()];
+        let results = runtime
+            .run(app.get_program(sum_stats).unwrap(), arguments, &public_key)
+            .unwrap();
+
+        let actual: [Rational; STATS_SIZE] = runtime.decrypt(&results[0], &private_key).unwrap();
+        let actual: Vec<f64> = actual.into_iter().map(f64::from).collect();
+        let expected = sum_stats_impl(stats_a, stats_b);
+        for i in 0..STATS_SIZE {
+            assert_eq!(actual[i], expected[i]);
+        }
+    }
+
+    #[test]
+    fn test_fhe_fit_stats() {
+        let (app, runtime, public_key, private_key) = make_app();
+
+        let stats = local_stats(INPUTS_X, INPUTS_Y);
+        let enc_stats = encrypt_vec::<Rational>(stats, &runtime, &public_key);
+
+        let arguments: Vec<FheProgramInput> = vec![enc_stats.into()];
+        let results = runtime
+            .run(app.get_program(fit_stats).unwrap(), arguments, &public_key)
+            .unwrap();
+
+        let actual: (f64, f64) = (
+            runtime.decrypt::<Rational>(&results[0], &private_key).unwrap().into(),
+            runtime.decrypt::<Rational>(&results[1], &private_key).unwrap().into(),
+        );
+        let expected = fit_stats_impl(stats);
+
+        assert!(f64::abs(actual.0 - expected.0) < 1e-5);
+        assert!(f64::abs(actual.1 - expected.1) < 1e-5);
+    }
+
+    #[test]
+    fn test_fhe_rmse() {
+        let (app, runtime, public_key, private_key) = make_app();
+
+        let enc_y_pred = encrypt_vec::<Rational>(INPUTS_X, &runtime, &public_key);
+        let enc_y_test = encrypt_vec::<Rational>(INPUTS_Y, &runtime, &public_key);
+
+        let arguments: Vec<FheProgramInput> = vec![enc_y_pred.into(), enc_y_test.into()];
+        let results = runtime
+            .run(app.get_program(rmse).unwrap(), arguments, &public_key)
+            .unwrap();
+
+        let actual: f64 = runtime
+            .decrypt::<Rational>(&results[0], &private_key)
+            .unwrap()
+            .into();
+        let expected = rmse_impl(INPUTS_X, INPUTS_Y);
+
+        assert!(f64::abs(actual - expected) < 1e-5);
+    }
+
+    #[test]
+    fn test_rmse_near_zero_mse() {
+        // A perfect fit (e.g. test_linear_regression's y = 2x) drives mse to
+        // 0, where the Newton-Raphson sqrt in rmse_impl converges slowest.
+        let y = [1f64, 2f64, 3f64, 4f64, 5f64];
+        assert!(rmse_impl(y, y) < 1e-5);
+        assert!(sqrt_newton_raphson_impl(0f64, RMSE_SQRT_SEED, NR_ITERATIONS) < 1e-5);
+    }
 }
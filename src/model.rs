@@ -3,7 +3,8 @@ use sunscreen::{
     PublicKey, Runtime,
 };
 
-use crate::math::{self, VEC_SIZE};
+use crate::commitment::Commitment;
+use crate::math::{self, STATS_SIZE, VEC_SIZE};
 
 fn root_mean_squared_error(actual: &Vec<f64>, predicted: &[f64]) -> f64 {
     let mut sum_error = 0f64;
@@ -29,6 +30,10 @@ impl Client {
         let fhe_app = Compiler::new()
             .fhe_program(math::fit)
             .fhe_program(math::predict)
+            .fhe_program(math::predict_batch)
+            .fhe_program(math::sum_stats)
+            .fhe_program(math::fit_stats)
+            .fhe_program(math::rmse)
             .compile()
             .unwrap();
         let runtime = Runtime::new(fhe_app.params()).unwrap();
@@ -62,9 +67,48 @@ impl Client {
         x.into()
     }
 
+    pub fn decrypt_vec(&self, ciphertext: &Ciphertext) -> Vec<f64> {
+        let x: [Rational; VEC_SIZE] = self.runtime.decrypt(ciphertext, &self.private_key).unwrap();
+        x.into_iter().map(|value| value.into()).collect()
+    }
+
+    /// Reduces this client's rows to the sufficient statistics a federated
+    /// fit needs (`S_x`, `S_y`, `S_xy`, `S_xx`, row count `n`).
+    pub fn local_stats(x_values: &[f64], y_values: &[f64]) -> [f64; STATS_SIZE] {
+        let n = x_values.len() as f64;
+        let s_x: f64 = x_values.iter().sum();
+        let s_y: f64 = y_values.iter().sum();
+        let s_xy: f64 = x_values.iter().zip(y_values).map(|(x, y)| x * y).sum();
+        let s_xx: f64 = x_values.iter().map(|x| x * x).sum();
+        [s_x, s_y, s_xy, s_xx, n]
+    }
+
+    pub fn encrypt_stats(&self, stats: [f64; STATS_SIZE]) -> sunscreen::Ciphertext {
+        let cast_stats: [_; STATS_SIZE] = stats
+            .into_iter()
+            .map(|x| Rational::try_from(x).unwrap())
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap_or_else(|_| panic!("Failed to convert to array of length {}", STATS_SIZE));
+        self.runtime.encrypt(cast_stats, &self.public_key).unwrap()
+    }
+
     pub fn evaluate(&self, y_test: &Vec<f64>, y_pred: &[f64]) -> f64 {
         root_mean_squared_error(y_test, y_pred)
     }
+
+    /// Rejects a server's result before spending a decryption on it unless it
+    /// matches the commitment to exactly these input and output ciphertexts.
+    /// Only catches tampering/corruption/replay in transit, not a server that
+    /// computed the wrong thing on purpose — see [`Commitment`].
+    pub fn check_commitment(
+        &self,
+        commitment: &Commitment,
+        inputs: &[Ciphertext],
+        outputs: &[Ciphertext],
+    ) -> bool {
+        commitment.matches(inputs, outputs)
+    }
 }
 struct Server {
     client_public_key: PublicKey,
@@ -86,6 +130,26 @@ impl Server {
         LinearRegression::new(self.client_public_key.clone()).fit(x_values, y_values)
     }
 
+    /// Trains a model from several clients' encrypted sufficient statistics.
+    pub fn fit_from_stats(&self, client_stats: &[Ciphertext]) -> LinearRegressionEncryptedModel {
+        LinearRegression::new(self.client_public_key.clone()).fit_from_stats(client_stats)
+    }
+
+    /// Like [`Server::fit`], but also returns a [`Commitment`] to the
+    /// training ciphertexts and resulting model, so the client can detect a
+    /// result tampered with in transit before decrypting it.
+    pub fn fit_with_commitment(
+        &self,
+        x_values: Ciphertext,
+        y_values: Ciphertext,
+    ) -> (LinearRegressionEncryptedModel, Commitment) {
+        let inputs = vec![x_values.clone(), y_values.clone()];
+        let model = self.fit(x_values, y_values);
+        let outputs = vec![model.intercept.clone(), model.coefficient.clone()];
+        let commitment = Commitment::new("fit", &inputs, &outputs);
+        (model, commitment)
+    }
+
     pub fn predict(
         &self,
         model: &LinearRegressionEncryptedModel,
@@ -93,6 +157,18 @@ impl Server {
     ) -> Ciphertext {
         LinearRegression::new(self.client_public_key.clone()).predict(model, x_values)
     }
+
+    pub fn predict_list(
+        &self,
+        model: &LinearRegressionEncryptedModel,
+        x_values: &Ciphertext,
+    ) -> Ciphertext {
+        LinearRegression::new(self.client_public_key.clone()).predict_list(model, x_values)
+    }
+
+    pub fn evaluate(&self, y_pred: &Ciphertext, y_test: &Ciphertext) -> Ciphertext {
+        LinearRegression::new(self.client_public_key.clone()).evaluate(y_pred, y_test)
+    }
 }
 
 pub struct LinearRegressionEncryptedModel {
@@ -112,6 +188,10 @@ impl LinearRegression {
         let fhe_app = Compiler::new()
             .fhe_program(math::fit)
             .fhe_program(math::predict)
+            .fhe_program(math::predict_batch)
+            .fhe_program(math::sum_stats)
+            .fhe_program(math::fit_stats)
+            .fhe_program(math::rmse)
             .compile()
             .unwrap();
         let runtime = Runtime::new(fhe_app.params()).unwrap();
@@ -127,16 +207,12 @@ impl LinearRegression {
         x_values: Ciphertext,
         y_values: Ciphertext,
     ) -> LinearRegressionEncryptedModel {
-        let divisor: f64 = 1.0 / VEC_SIZE as f64;
-        let enc_divisor = self
+        let enc_one = self
             .runtime
-            .encrypt(
-                Rational::try_from(divisor).unwrap(),
-                &self.client_public_key,
-            )
+            .encrypt(Rational::try_from(1.0).unwrap(), &self.client_public_key)
             .unwrap();
         let arguments: Vec<FheProgramInput> =
-            vec![x_values.into(), y_values.into(), enc_divisor.into()];
+            vec![x_values.into(), y_values.into(), enc_one.into()];
 
         let results = self
             .runtime
@@ -155,6 +231,40 @@ impl LinearRegression {
         }
     }
 
+    /// Sums each client's encrypted sufficient statistics into one running
+    /// total, then fits a single model from that total.
+    pub fn fit_from_stats(&self, client_stats: &[Ciphertext]) -> LinearRegressionEncryptedModel {
+        let mut aggregated = client_stats[0].clone();
+        for stats in &client_stats[1..] {
+            let arguments: Vec<FheProgramInput> =
+                vec![aggregated.into(), stats.clone().into()];
+            aggregated = self
+                .runtime
+                .run(
+                    self.fhe_app.get_program(math::sum_stats).unwrap(),
+                    arguments,
+                    &self.client_public_key,
+                )
+                .unwrap()[0]
+                .clone();
+        }
+
+        let arguments: Vec<FheProgramInput> = vec![aggregated.into()];
+        let results = self
+            .runtime
+            .run(
+                self.fhe_app.get_program(math::fit_stats).unwrap(),
+                arguments,
+                &self.client_public_key,
+            )
+            .unwrap();
+
+        LinearRegressionEncryptedModel {
+            intercept: results[0].clone(),
+            coefficient: results[1].clone(),
+        }
+    }
+
     pub fn predict(&self, model: &LinearRegressionEncryptedModel, x: &Ciphertext) -> Ciphertext {
         let arguments: Vec<FheProgramInput> = vec![
             model.intercept.clone().into(),
@@ -174,13 +284,35 @@ impl LinearRegression {
     pub fn predict_list(
         &self,
         model: &LinearRegressionEncryptedModel,
-        x_values: &Vec<Ciphertext>,
-    ) -> Vec<Ciphertext> {
-        let mut predictions = Vec::new();
-        (0..x_values.len()).for_each(|i| {
-            predictions.push(self.predict(model, &x_values[i]));
-        });
-        predictions
+        x_values: &Ciphertext,
+    ) -> Ciphertext {
+        let arguments: Vec<FheProgramInput> = vec![
+            model.intercept.clone().into(),
+            model.coefficient.clone().into(),
+            x_values.clone().into(),
+        ];
+        self.runtime
+            .run(
+                self.fhe_app.get_program(math::predict_batch).unwrap(),
+                arguments,
+                &self.client_public_key,
+            )
+            .unwrap()[0]
+            .clone()
+    }
+
+    /// Computes the encrypted RMSE between a batch of predictions and the
+    /// matching encrypted test labels.
+    pub fn evaluate(&self, y_pred: &Ciphertext, y_test: &Ciphertext) -> Ciphertext {
+        let arguments: Vec<FheProgramInput> = vec![y_pred.clone().into(), y_test.clone().into()];
+        self.runtime
+            .run(
+                self.fhe_app.get_program(math::rmse).unwrap(),
+                arguments,
+                &self.client_public_key,
+            )
+            .unwrap()[0]
+            .clone()
     }
 }
 
@@ -203,19 +335,18 @@ fn test_linear_regression() {
     // Server trains model on encrypted training data
     let model = server.fit(enc_x_train, enc_y_train);
 
-    // Client sends encrypted test data to Server
-    let enc_x_test = client.encrypt(x_test[0]);
+    // Client sends encrypted test data to Server, packed as a single ciphertext
+    let enc_x_test = client.encrypt_vec(&x_test);
 
-    // Server predicts on encrypted test data and sends back encrypted prediction
-    // TODO: Implement predict_list in FHE
-    let enc_y_pred = server.predict(&model, &enc_x_test);
+    // Server predicts on the whole packed batch in a single FHE run
+    let enc_y_pred = server.predict_list(&model, &enc_x_test);
 
-    // Client decrypts prediction
-    let y_pred = client.decrypt(&enc_y_pred);
-    assert_eq!(y_pred, y_test[0]);
+    // Client decrypts predictions
+    let y_pred = client.decrypt_vec(&enc_y_pred);
+    assert_eq!(y_pred, y_test);
 
     // Client evaluates model on test data and prints RMSE
-    client.evaluate(&y_test, &[y_pred]);
+    client.evaluate(&y_test, &y_pred);
 
     // TODO: Test evaluation
     // let x_test = vec![6f64, 7f64, 8f64, 9f64, 10f64];
@@ -226,3 +357,72 @@ fn test_linear_regression() {
     // assert_eq!(linear_regression.predict(5f64), 10f64);
     // assert_eq!(linear_regression.evaluate(&x_train, &y_train), 0f64);
 }
+
+#[test]
+fn test_federated_fit() {
+    // Two clients, each holding a disjoint part (2 and 3 rows) of the same line y = 2x.
+    let client_a_x = vec![1f64, 2f64];
+    let client_a_y = vec![2f64, 4f64];
+    let client_b_x = vec![3f64, 4f64, 5f64];
+    let client_b_y = vec![6f64, 8f64, 10f64];
+
+    let client = Client::new();
+    let server = Server::new(&client.public_key);
+
+    // Each client reduces its private rows to sufficient statistics locally,
+    // and only uploads those encrypted scalars.
+    let stats_a = Client::local_stats(&client_a_x, &client_a_y);
+    let stats_b = Client::local_stats(&client_b_x, &client_b_y);
+    let enc_stats = vec![client.encrypt_stats(stats_a), client.encrypt_stats(stats_b)];
+
+    // Server aggregates the encrypted statistics and fits a model without
+    // ever seeing any client's rows.
+    let model = server.fit_from_stats(&enc_stats);
+
+    let enc_x_test = client.encrypt(6f64);
+    let enc_y_pred = server.predict(&model, &enc_x_test);
+    let y_pred = client.decrypt(&enc_y_pred);
+
+    assert!(f64::abs(y_pred - 12f64) < 1e-5);
+}
+
+#[test]
+fn test_fit_with_commitment() {
+    let x_train = vec![1f64, 2f64, 3f64, 4f64, 5f64];
+    let y_train = vec![2f64, 4f64, 6f64, 8f64, 10f64];
+
+    let client = Client::new();
+    let server = Server::new(&client.public_key);
+
+    let enc_x_train = client.encrypt_vec(&x_train);
+    let enc_y_train = client.encrypt_vec(&y_train);
+
+    let (model, commitment) = server.fit_with_commitment(enc_x_train.clone(), enc_y_train.clone());
+    let inputs = vec![enc_x_train, enc_y_train];
+    let outputs = vec![model.intercept.clone(), model.coefficient.clone()];
+
+    assert!(client.check_commitment(&commitment, &inputs, &outputs));
+
+    // A result that doesn't match the committed inputs/outputs is rejected.
+    let tampered_outputs = vec![model.coefficient.clone(), model.intercept.clone()];
+    assert!(!client.check_commitment(&commitment, &inputs, &tampered_outputs));
+}
+
+#[test]
+fn test_encrypted_evaluate() {
+    let y_pred = vec![1f64, 2f64, 3f64, 4f64, 5f64];
+    let y_test = vec![0.5f64, 1f64, 2.5f64, 3f64, 3.25f64];
+
+    let client = Client::new();
+    let server = Server::new(&client.public_key);
+
+    let enc_y_pred = client.encrypt_vec(&y_pred);
+    let enc_y_test = client.encrypt_vec(&y_test);
+
+    // Server reports RMSE against the encrypted test labels, never decrypting them.
+    let enc_rmse = server.evaluate(&enc_y_pred, &enc_y_test);
+    let rmse = client.decrypt(&enc_rmse);
+
+    let expected = client.evaluate(&y_test, &y_pred);
+    assert!(f64::abs(rmse - expected) < 1e-5);
+}
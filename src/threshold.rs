@@ -0,0 +1,343 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sunscreen::{types::bfv::Rational, Ciphertext, Compiler, PrivateKey, PublicKey, Runtime};
+
+use crate::math::{self, VEC_SIZE};
+use crate::model::LinearRegression;
+
+/// 2^61 - 1, a Mersenne prime large enough to hold a 7-byte chunk of a
+/// serialized [`PrivateKey`] while keeping the finite-field arithmetic below
+/// simple and overflow-free in `u128`.
+const FIELD_PRIME: u64 = 2_305_843_009_213_693_951;
+
+fn field_add(a: u64, b: u64) -> u64 {
+    ((a as u128 + b as u128) % FIELD_PRIME as u128) as u64
+}
+
+fn field_sub(a: u64, b: u64) -> u64 {
+    field_add(a, FIELD_PRIME - (b % FIELD_PRIME))
+}
+
+fn field_mul(a: u64, b: u64) -> u64 {
+    ((a as u128 * b as u128) % FIELD_PRIME as u128) as u64
+}
+
+fn field_pow(base: u64, mut exp: u64) -> u64 {
+    let mut result = 1u64;
+    let mut base = base % FIELD_PRIME;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = field_mul(result, base);
+        }
+        base = field_mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+fn field_inv(a: u64) -> u64 {
+    // Fermat's little theorem: a^(p-2) is a's multiplicative inverse mod prime p.
+    field_pow(a, FIELD_PRIME - 2)
+}
+
+/// A minimal xorshift64 PRNG (13/7/17 shifts) whose state is also multiplied
+/// by the xorshift64* constant on every step. This project has no dependency on a secure
+/// RNG crate, and the surrounding FHE parameters are themselves demo-grade,
+/// so this is sized for a working toy threshold scheme, not production use.
+struct Prng(u64);
+
+impl Prng {
+    fn new(seed: u64) -> Self {
+        Self(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 = self.0.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        self.0
+    }
+
+    fn next_field(&mut self) -> u64 {
+        self.next_u64() % FIELD_PRIME
+    }
+}
+
+fn bytes_to_field_elements(bytes: &[u8]) -> Vec<u64> {
+    bytes
+        .chunks(7)
+        .map(|chunk| {
+            let mut buf = [0u8; 8];
+            buf[..chunk.len()].copy_from_slice(chunk);
+            u64::from_le_bytes(buf)
+        })
+        .collect()
+}
+
+fn field_elements_to_bytes(elements: &[u64], original_len: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(elements.len() * 7);
+    for element in elements {
+        bytes.extend_from_slice(&element.to_le_bytes()[..7]);
+    }
+    bytes.truncate(original_len);
+    bytes
+}
+
+/// Splits `secret` into `n` Shamir shares reconstructable by any `t` of them,
+/// via a random degree-`(t - 1)` polynomial over `GF(FIELD_PRIME)` evaluated
+/// at `x = 1..=n`.
+fn split_secret(secret: u64, n: usize, t: usize, rng: &mut Prng) -> Vec<(u64, u64)> {
+    let mut coefficients = vec![secret % FIELD_PRIME];
+    coefficients.extend((1..t).map(|_| rng.next_field()));
+
+    (1..=n as u64)
+        .map(|x| {
+            let y = coefficients
+                .iter()
+                .enumerate()
+                .fold(0u64, |acc, (i, c)| field_add(acc, field_mul(*c, field_pow(x, i as u64))));
+            (x, y)
+        })
+        .collect()
+}
+
+/// Reconstructs the secret at `x = 0` via Lagrange interpolation over `points`.
+fn reconstruct_secret(points: &[(u64, u64)]) -> u64 {
+    points.iter().enumerate().fold(0u64, |secret, (i, &(xi, yi))| {
+        let (num, den) = points.iter().enumerate().filter(|(j, _)| *j != i).fold(
+            (1u64, 1u64),
+            |(num, den), (_, &(xj, _))| (field_mul(num, xj), field_mul(den, field_sub(xj, xi))),
+        );
+        field_add(secret, field_mul(yi, field_mul(num, field_inv(den))))
+    })
+}
+
+/// One holder's share of a Shamir-split [`PrivateKey`], with enough context
+/// (`secret_len`, `threshold`) for [`combine_partials`] to reconstruct the
+/// key once enough shares are present.
+#[derive(Clone)]
+pub struct KeyShare {
+    index: u64,
+    chunk_shares: Vec<u64>,
+    secret_len: usize,
+    threshold: usize,
+}
+
+/// A holder's contribution towards decrypting a specific ciphertext.
+///
+/// Sunscreen exposes no BFV partial-decryption primitive (no API to combine
+/// key shares directly against ciphertext/polynomial state), so this is just
+/// this holder's key share alongside the ciphertext it applies to. The real
+/// work — reconstructing the private key from `t` shares and decrypting —
+/// happens once in [`combine_partials`]. That means whoever calls
+/// `combine_partials` transiently holds the full private key for the
+/// duration of the call, not just a share of the plaintext; the `t`-of-`n`
+/// property only gates *who can trigger* a decryption, not what the
+/// combiner learns while doing it.
+#[derive(Clone)]
+pub struct PartialDecryption {
+    share: KeyShare,
+    ciphertext: Ciphertext,
+}
+
+/// Requires a quorum of `t` holders to cooperate before a model's
+/// intercept/coefficient can be decrypted, by Shamir-secret-sharing the BFV
+/// secret key into `n` shares with reconstruction threshold `t`.
+#[allow(dead_code)]
+pub struct ThresholdClient {
+    runtime: Runtime,
+}
+
+#[allow(dead_code)]
+impl ThresholdClient {
+    /// Compiles the same program set as `model.rs`'s `Client` and
+    /// [`LinearRegression`], so ciphertexts and keys produced here
+    /// interoperate with the BFV scheme parameters those use — a model fit
+    /// under a threshold-shared key can only be threshold-decrypted if both
+    /// sides agree on those parameters.
+    pub fn new() -> Self {
+        let fhe_app = Compiler::new()
+            .fhe_program(math::fit)
+            .fhe_program(math::predict)
+            .fhe_program(math::predict_batch)
+            .fhe_program(math::sum_stats)
+            .fhe_program(math::fit_stats)
+            .fhe_program(math::rmse)
+            .compile()
+            .unwrap();
+        let runtime = Runtime::new(fhe_app.params()).unwrap();
+        Self { runtime }
+    }
+
+    pub fn encrypt(&self, input: f64, public_key: &PublicKey) -> Ciphertext {
+        let x = Rational::try_from(input).unwrap();
+        self.runtime.encrypt(x, public_key).unwrap()
+    }
+
+    pub fn encrypt_vec(&self, inputs: &[f64], public_key: &PublicKey) -> Ciphertext {
+        let cast_inputs: [_; VEC_SIZE] = inputs
+            .iter()
+            .map(|x| Rational::try_from(*x).unwrap())
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap_or_else(|_| panic!("Failed to convert to array of length {}", VEC_SIZE));
+        self.runtime.encrypt(cast_inputs, public_key).unwrap()
+    }
+
+    /// Generates a single BFV keypair and splits the secret key into `n`
+    /// shares, any `t` of which can later reconstruct it.
+    pub fn generate_threshold_keys(&self, n: usize, t: usize) -> (PublicKey, Vec<KeyShare>) {
+        assert!(t >= 1 && t <= n, "threshold must satisfy 1 <= t <= n");
+
+        let (public_key, private_key) = self.runtime.generate_keys().unwrap();
+        let secret_bytes = bincode::serialize(&private_key).unwrap();
+        let elements = bytes_to_field_elements(&secret_bytes);
+
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        let mut rng = Prng::new(seed);
+
+        let mut chunk_shares_by_holder: Vec<Vec<u64>> = vec![Vec::with_capacity(elements.len()); n];
+        for element in &elements {
+            for (holder, (_, share)) in split_secret(*element, n, t, &mut rng).into_iter().enumerate() {
+                chunk_shares_by_holder[holder].push(share);
+            }
+        }
+
+        let shares = chunk_shares_by_holder
+            .into_iter()
+            .enumerate()
+            .map(|(i, chunk_shares)| KeyShare {
+                index: (i + 1) as u64,
+                chunk_shares,
+                secret_len: secret_bytes.len(),
+                threshold: t,
+            })
+            .collect();
+
+        (public_key, shares)
+    }
+
+    /// Binds one holder's key share to the ciphertext it will help decrypt.
+    pub fn partial_decrypt(share: &KeyShare, ciphertext: &Ciphertext) -> PartialDecryption {
+        PartialDecryption {
+            share: share.clone(),
+            ciphertext: ciphertext.clone(),
+        }
+    }
+
+    /// Reconstructs the private key from the first `t` partials' shares and
+    /// decrypts the first partial's ciphertext; the other partials'
+    /// ciphertexts are not compared with it. Rejects (panics) when fewer
+    /// than `t` partials are supplied.
+    ///
+    /// The reconstructed field elements and serialized key bytes are
+    /// overwritten with zeros once the key is deserialized; the deserialized
+    /// `PrivateKey` itself is dropped at the end of the call without being
+    /// zeroed, so the key exists in memory for the duration of the call —
+    /// see the [`PartialDecryption`] doc comment for why.
+    pub fn combine_partials(&self, partials: &[PartialDecryption]) -> f64 {
+        assert!(!partials.is_empty(), "threshold decryption requires at least 1 partial");
+        let threshold = partials[0].share.threshold;
+        assert!(
+            partials.len() >= threshold,
+            "threshold decryption requires at least {} partials, got {}",
+            threshold,
+            partials.len()
+        );
+
+        let secret_len = partials[0].share.secret_len;
+        let chunk_count = partials[0].share.chunk_shares.len();
+        let ciphertext = &partials[0].ciphertext;
+
+        let mut elements: Vec<u64> = (0..chunk_count)
+            .map(|chunk| {
+                let points: Vec<(u64, u64)> = partials[..threshold]
+                    .iter()
+                    .map(|p| (p.share.index, p.share.chunk_shares[chunk]))
+                    .collect();
+                reconstruct_secret(&points)
+            })
+            .collect();
+
+        let mut secret_bytes = field_elements_to_bytes(&elements, secret_len);
+        let private_key: PrivateKey = bincode::deserialize(&secret_bytes).unwrap();
+        elements.iter_mut().for_each(|e| *e = 0);
+        secret_bytes.iter_mut().for_each(|b| *b = 0);
+
+        let plaintext: Rational = self.runtime.decrypt(ciphertext, &private_key).unwrap();
+        plaintext.into()
+    }
+}
+
+#[test]
+fn test_threshold_decrypt() {
+    let client = ThresholdClient::new();
+    let (public_key, shares) = client.generate_threshold_keys(5, 3);
+
+    let enc_value = client.encrypt(4.5, &public_key);
+
+    let partials: Vec<PartialDecryption> = shares[..3]
+        .iter()
+        .map(|share| ThresholdClient::partial_decrypt(share, &enc_value))
+        .collect();
+
+    let decrypted = client.combine_partials(&partials);
+    assert!(f64::abs(decrypted - 4.5) < 1e-5);
+}
+
+#[test]
+#[should_panic(expected = "threshold decryption requires at least 3 partials")]
+fn test_threshold_decrypt_rejects_below_threshold() {
+    let client = ThresholdClient::new();
+    let (public_key, shares) = client.generate_threshold_keys(5, 3);
+
+    let enc_value = client.encrypt(4.5, &public_key);
+
+    let partials: Vec<PartialDecryption> = shares[..2]
+        .iter()
+        .map(|share| ThresholdClient::partial_decrypt(share, &enc_value))
+        .collect();
+
+    client.combine_partials(&partials);
+}
+
+#[test]
+#[should_panic(expected = "threshold decryption requires at least 1 partial")]
+fn test_threshold_decrypt_rejects_empty_partials() {
+    let client = ThresholdClient::new();
+    client.combine_partials(&[]);
+}
+
+/// A model fit under a threshold-shared key (not a lone `Client`'s key) can
+/// still only be decrypted by a quorum of `t` holders cooperating.
+#[test]
+fn test_threshold_decrypt_fitted_model() {
+    let x_train = vec![1f64, 2f64, 3f64, 4f64, 5f64];
+    let y_train = vec![2f64, 4f64, 6f64, 8f64, 10f64];
+
+    let threshold_client = ThresholdClient::new();
+    let (public_key, shares) = threshold_client.generate_threshold_keys(5, 3);
+
+    let enc_x_train = threshold_client.encrypt_vec(&x_train, &public_key);
+    let enc_y_train = threshold_client.encrypt_vec(&y_train, &public_key);
+
+    let model = LinearRegression::new(public_key).fit(enc_x_train, enc_y_train);
+
+    let decrypt_field = |ciphertext: &Ciphertext| {
+        let partials: Vec<PartialDecryption> = shares[..3]
+            .iter()
+            .map(|share| ThresholdClient::partial_decrypt(share, ciphertext))
+            .collect();
+        threshold_client.combine_partials(&partials)
+    };
+
+    let intercept = decrypt_field(&model.intercept);
+    let coefficient = decrypt_field(&model.coefficient);
+
+    assert!(f64::abs(intercept - 0.0) < 1e-5);
+    assert!(f64::abs(coefficient - 2.0) < 1e-5);
+}